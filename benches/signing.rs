@@ -0,0 +1,68 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use s3_presigned_urls::s3_compatible_signing_client::PresignedMultipartParameters;
+use s3_presigned_urls::S3CompatibleSigningClient;
+
+const BATCH_PARTS: u32 = 1_000;
+
+fn signing_client() -> S3CompatibleSigningClient {
+    S3CompatibleSigningClient::new(
+        "AKIDEXAMPLE",
+        "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        "s3.amazonaws.com",
+        "us-east-1",
+        "session-claqbxlfv0000ix0lx6inf7sd",
+    )
+}
+
+fn single(c: &mut Criterion) {
+    let mut group = c.benchmark_group("single");
+    group.throughput(Throughput::Elements(1));
+
+    // the client is kept, so after the first signature the signing key comes from its cache
+    let client = signing_client();
+    group.bench_function("cached signing key", |b| {
+        b.iter(|| {
+            client
+                .presigned_get_url(black_box("example-bucket"), black_box("my-movie.m2ts"), 600)
+                .unwrap()
+        })
+    });
+
+    // a new client each time derives the signing key afresh
+    group.bench_function("uncached signing key", |b| {
+        b.iter_batched(
+            signing_client,
+            |client| {
+                client
+                    .presigned_get_url(black_box("example-bucket"), black_box("my-movie.m2ts"), 600)
+                    .unwrap()
+            },
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+fn batch(c: &mut Criterion) {
+    let mut group = c.benchmark_group("batch");
+    group.throughput(Throughput::Elements(BATCH_PARTS.into()));
+    let client = signing_client();
+    let data = PresignedMultipartParameters {
+        bucket: "example-bucket",
+        key: "my-movie.m2ts",
+        parts: BATCH_PARTS,
+        upload_id: "VCVsb2FkIElEIGZvciBlbZZpbmcncyBteS1tb3ZpZS5tMnRzIHVwbG9hZR",
+        expiry: 600,
+    };
+    group.bench_function("multipart put", |b| {
+        b.iter(|| {
+            client
+                .presigned_multipart_put_url(black_box(&data))
+                .unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, single, batch);
+criterion_main!(benches);
@@ -0,0 +1,1459 @@
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use url::Url;
+use wasm_bindgen::prelude::*;
+
+use crate::log;
+use crate::multipart_session::MultipartSession;
+use crate::s3_compatible_signing_client::{
+    url_parts, Clock, GetRequest, PresignedMultipartParameters, PutRequest,
+    S3CompatibleSigningClient, SystemClock,
+};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BackblazeAuthResponse {
+    // absolute_minimum_part_size: i64,
+    // authorization_token: String,
+    // api_url: String,
+    // download_url: String,
+    // recommended_part_size: i64,
+    s3_api_url: String,
+}
+
+/// Region label from a Backblaze S3 endpoint, such as `us-west-004` from
+/// `s3.us-west-004.backblazeb2.com`.  Backblaze signs with this label as it is, so it is not
+/// normalised.
+fn region_from_s3_api_url(s3_api_url: &str) -> Option<&str> {
+    s3_api_url.split('.').nth(1)
+}
+
+const BACKBLAZE_AUTHORISE_ACCOUNT_URL: &str =
+    "https://api.backblazeb2.com/b2api/v2/b2_authorize_account";
+
+/// Largest authorisation response body read, in bytes.  The `b2_authorize_account` response is
+/// well under this, so anything larger is not read in full.
+const MAXIMUM_AUTH_RESPONSE_LENGTH: usize = 16_384;
+
+/// Lifetime of a Backblaze authorisation, in hours.  The `b2_authorize_account` response carries
+/// no expiry, but its token is valid for 24 hours from issue.
+const BACKBLAZE_AUTHORISATION_LIFETIME_HOURS: i64 = 24;
+
+/// Longest part of an error response body included in an authorisation error message.
+const ERROR_BODY_SNIPPET_LENGTH: usize = 200;
+
+/// Fetches the provider authorisation response body, such as from `b2_authorize_account`, or
+/// describes why it could not.  Tests swap in a canned response in place of the provider API.
+trait AuthoriseTransport {
+    async fn authorise(&self, authorisation_header: HeaderValue) -> Result<String, String>;
+}
+
+/// Connection pool settings for the HTTP client used to authorise.  A server re-authorising
+/// periodically can keep connections alive between calls, or cap how many sit idle.  The browser
+/// manages connections and certificates in WASM, so these settings have no effect there.
+#[cfg_attr(target_arch = "wasm32", allow(dead_code))]
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionPool {
+    idle_timeout: Option<Duration>,
+    max_idle_per_host: Option<usize>,
+    #[cfg(not(target_arch = "wasm32"))]
+    root_certificates: Vec<reqwest::Certificate>,
+    #[cfg(not(target_arch = "wasm32"))]
+    accept_invalid_certificates: bool,
+}
+
+impl ConnectionPool {
+    /// Closes connections which have been idle for longer than `idle_timeout`.
+    pub fn with_idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self.idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Keeps at most `max_idle_per_host` idle connections open to each host.
+    pub fn with_max_idle_per_host(mut self, max_idle_per_host: usize) -> Self {
+        self.max_idle_per_host = Some(max_idle_per_host);
+        self
+    }
+
+    /// Trusts the PEM encoded `certificate` as a root, in addition to the system roots, for
+    /// example for an on-premises gateway with a certificate signed by a private CA.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn with_root_certificate(mut self, certificate: &[u8]) -> Result<Self, reqwest::Error> {
+        self.root_certificates
+            .push(reqwest::Certificate::from_pem(certificate)?);
+        Ok(self)
+    }
+
+    /// Accepts any server certificate, including self-signed and expired ones.  This turns off
+    /// protection against an attacker posing as the endpoint, so only opt in for a trusted
+    /// network, and prefer `with_root_certificate` where the gateway certificate is known.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn danger_accept_invalid_certificates(mut self, accept_invalid_certificates: bool) -> Self {
+        self.accept_invalid_certificates = accept_invalid_certificates;
+        self
+    }
+
+    /// An HTTP client with these settings, failing rather than falling back to defaults, which
+    /// would drop any configured root certificate.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn client(&self) -> Result<reqwest::Client, String> {
+        let mut builder = reqwest::Client::builder();
+        if let Some(value) = self.idle_timeout {
+            builder = builder.pool_idle_timeout(value);
+        }
+        if let Some(value) = self.max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(value);
+        }
+        for certificate in &self.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        if self.accept_invalid_certificates {
+            builder = builder.danger_accept_invalid_certs(true);
+        }
+        builder
+            .build()
+            .map_err(|error| format!("Unable to apply connection pool settings: {error}"))
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn client(&self) -> Result<reqwest::Client, String> {
+        Ok(reqwest::Client::new())
+    }
+}
+
+struct ApiTransport {
+    url: String,
+    client: reqwest::Client,
+    maximum_response_length: usize,
+}
+
+impl ApiTransport {
+    fn with_pool(url: &str, pool: &ConnectionPool) -> Result<Self, String> {
+        Ok(ApiTransport {
+            url: url.to_string(),
+            client: pool.client()?,
+            maximum_response_length: MAXIMUM_AUTH_RESPONSE_LENGTH,
+        })
+    }
+}
+
+impl Default for ApiTransport {
+    fn default() -> Self {
+        ApiTransport {
+            url: BACKBLAZE_AUTHORISE_ACCOUNT_URL.to_string(),
+            client: reqwest::Client::new(),
+            maximum_response_length: MAXIMUM_AUTH_RESPONSE_LENGTH,
+        }
+    }
+}
+
+/// Reads the `response` body, failing once it is longer than `maximum_length` bytes, rather than
+/// buffering whatever the server sends.
+#[cfg(not(target_arch = "wasm32"))]
+async fn limited_text(
+    mut response: reqwest::Response,
+    maximum_length: usize,
+) -> Result<String, String> {
+    let too_long = || format!("Auth response is longer than {maximum_length} bytes");
+    if response
+        .content_length()
+        .is_some_and(|value| value > maximum_length as u64)
+    {
+        return Err(too_long());
+    }
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|_| String::from("Error reading auth response from backblaze"))?
+    {
+        if body.len() + chunk.len() > maximum_length {
+            return Err(too_long());
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Reads the `response` body, failing when it is longer than `maximum_length` bytes.  The browser
+/// fetch API offers no chunked read here, so only a declared length stops the read early.
+#[cfg(target_arch = "wasm32")]
+async fn limited_text(
+    response: reqwest::Response,
+    maximum_length: usize,
+) -> Result<String, String> {
+    let too_long = || format!("Auth response is longer than {maximum_length} bytes");
+    if response
+        .content_length()
+        .is_some_and(|value| value > maximum_length as u64)
+    {
+        return Err(too_long());
+    }
+    let body = response
+        .text()
+        .await
+        .map_err(|_| String::from("Error reading auth response from backblaze"))?;
+    if body.len() > maximum_length {
+        return Err(too_long());
+    }
+    Ok(body)
+}
+
+/// Reads up to about `maximum_length` bytes from the start of the `response` body, stopping there
+/// rather than reading the rest, for quoting in an error message.
+#[cfg(not(target_arch = "wasm32"))]
+async fn body_prefix(mut response: reqwest::Response, maximum_length: usize) -> String {
+    let mut body = Vec::new();
+    while body.len() < maximum_length {
+        match response.chunk().await {
+            Ok(Some(chunk)) => body.extend_from_slice(&chunk),
+            _ => break,
+        }
+    }
+    body.truncate(maximum_length);
+    String::from_utf8_lossy(&body).into_owned()
+}
+
+/// Reads the start of the `response` body, for quoting in an error message.  The browser fetch API
+/// offers no chunked read here, so the body is read in full.
+#[cfg(target_arch = "wasm32")]
+async fn body_prefix(response: reqwest::Response, maximum_length: usize) -> String {
+    let mut body = response.text().await.unwrap_or_default();
+    let mut length = maximum_length.min(body.len());
+    while !body.is_char_boundary(length) {
+        length -= 1;
+    }
+    body.truncate(length);
+    body
+}
+
+impl AuthoriseTransport for ApiTransport {
+    async fn authorise(&self, authorisation_header: HeaderValue) -> Result<String, String> {
+        let mut headers_map = HeaderMap::new();
+        headers_map.insert(AUTHORIZATION, authorisation_header);
+        let result = match self.client.get(&self.url).headers(headers_map).send().await {
+            Ok(res) => res,
+            Err(error) => return Err(format!("Error reaching backblaze for auth: {error}")),
+        };
+        let status = result.status();
+        // error responses may be an HTML page rather than JSON, so report the status and the start
+        // of the body, instead of failing to parse it; the length limit is only for auth responses
+        if !status.is_success() {
+            // a UTF-8 character is at most four bytes
+            let body = body_prefix(result, 4 * ERROR_BODY_SNIPPET_LENGTH).await;
+            let snippet: String = body
+                .trim()
+                .chars()
+                .take(ERROR_BODY_SNIPPET_LENGTH)
+                .collect();
+            return Err(format!(
+                "Backblaze auth failed with status {status}: {snippet}"
+            ));
+        }
+        limited_text(result, self.maximum_response_length).await
+    }
+}
+
+/// S3 compatible storage providers whose authorisation response names the account S3 endpoint.
+/// Adding a provider means adding a variant, with its authorisation URL, header and a parser for
+/// its response.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Provider {
+    Backblaze,
+}
+
+impl Provider {
+    fn authorisation_url(self) -> &'static str {
+        match self {
+            Provider::Backblaze => BACKBLAZE_AUTHORISE_ACCOUNT_URL,
+        }
+    }
+
+    fn authorisation_header(self, account_id: &str, account_auth_token: &str) -> String {
+        match self {
+            Provider::Backblaze => {
+                let combined_credential_value_base64 = format!("{account_id}:{account_auth_token}");
+                let authorisation_credentials =
+                    base64::encode_config(combined_credential_value_base64, base64::URL_SAFE);
+                format!("Basic {authorisation_credentials}")
+            }
+        }
+    }
+
+    /// The S3 endpoint and region named in the authorisation response `body`.
+    fn endpoint_from_response(self, body: &str) -> Option<(String, String)> {
+        match self {
+            Provider::Backblaze => backblaze_endpoint(body),
+        }
+    }
+}
+
+fn backblaze_endpoint(body: &str) -> Option<(String, String)> {
+    match serde_json::from_str::<BackblazeAuthResponse>(body) {
+        Ok(value) => {
+            let s3_api_url = match Url::parse(&value.s3_api_url) {
+                Ok(value) => value,
+                Err(_) => {
+                    console_log!("Unable to parse S3 API URL");
+                    return None;
+                }
+            };
+            let endpoint = match s3_api_url.domain() {
+                Some(value) => value,
+                None => {
+                    console_log!("Unable to parse S3 endpoint");
+                    return None;
+                }
+            };
+            let region = match region_from_s3_api_url(endpoint) {
+                Some(value) => value,
+                None => {
+                    console_log!("Unable to infer S3 region");
+                    return None;
+                }
+            };
+            Some((endpoint.to_string(), region.to_string()))
+        }
+        Err(_) => {
+            console_log!("Error getting auth from backblaze");
+            None
+        }
+    }
+}
+
+/// Authorises with `provider`, returning the S3 endpoint and region for the account, or `None`
+/// if authorisation failed.
+pub async fn authorise(
+    provider: Provider,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+) -> Option<(String, String)> {
+    let transport =
+        match ApiTransport::with_pool(provider.authorisation_url(), &ConnectionPool::default()) {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                return None;
+            }
+        };
+    authorise_with(
+        &transport,
+        provider,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+    )
+    .await
+}
+
+async fn authorise_backblaze_b2(
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+) -> Option<(String, String)> {
+    authorise(
+        Provider::Backblaze,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+    )
+    .await
+}
+
+/// Authorises with Backblaze through a client which is kept, so repeated authorisation reuses its
+/// pooled connections.
+pub struct BackblazeAuthoriser {
+    transport: ApiTransport,
+}
+
+impl BackblazeAuthoriser {
+    /// Fails when the `pool` settings cannot be applied to an HTTP client.
+    pub fn new(pool: &ConnectionPool) -> Result<Self, String> {
+        Ok(BackblazeAuthoriser {
+            transport: ApiTransport::with_pool(BACKBLAZE_AUTHORISE_ACCOUNT_URL, pool)?,
+        })
+    }
+
+    /// Fails authorisation when the response is longer than `maximum_response_length` bytes, in
+    /// place of the default 16 KiB.
+    pub fn with_maximum_response_length(mut self, maximum_response_length: usize) -> Self {
+        self.transport.maximum_response_length = maximum_response_length;
+        self
+    }
+
+    /// The S3 endpoint and region for the account, or `None` if authorisation failed.
+    pub async fn authorise(
+        &self,
+        s3_compatible_account_id: &str,
+        s3_compatible_account_auth_token: &str,
+    ) -> Option<(String, String)> {
+        authorise_with(
+            &self.transport,
+            Provider::Backblaze,
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+        )
+        .await
+    }
+}
+
+/// S3 endpoint and region from an authorisation, with the time the authorisation expires.
+struct CachedEndpoint {
+    endpoint: String,
+    region: String,
+    expires_at: DateTime<Utc>,
+}
+
+/// Authorises with Backblaze on first use, then keeps the S3 endpoint and region until the
+/// authorisation expires, authorising again on the first call after.  This saves authorising on
+/// every call without signing against a stale authorisation.
+pub struct BackblazeSession {
+    transport: ApiTransport,
+    account_id: String,
+    account_auth_token: String,
+    clock: Box<dyn Clock + Send + Sync>,
+    cached: Mutex<Option<CachedEndpoint>>,
+}
+
+impl BackblazeSession {
+    /// Fails when the `pool` settings cannot be applied to an HTTP client.
+    pub fn new(
+        account_id: &str,
+        account_auth_token: &str,
+        pool: &ConnectionPool,
+    ) -> Result<Self, String> {
+        Ok(BackblazeSession {
+            transport: ApiTransport::with_pool(BACKBLAZE_AUTHORISE_ACCOUNT_URL, pool)?,
+            account_id: account_id.into(),
+            account_auth_token: account_auth_token.into(),
+            clock: Box::new(SystemClock),
+            cached: Mutex::new(None),
+        })
+    }
+
+    /// Takes the time authorisations are checked against from `clock` rather than the system
+    /// clock.
+    pub fn with_clock<C: Clock + Send + Sync + 'static>(mut self, clock: C) -> Self {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// The S3 endpoint and region for the account, authorising only when there is no unexpired
+    /// authorisation, or `None` if authorisation failed.
+    pub async fn endpoint(&self) -> Option<(String, String)> {
+        self.endpoint_with(&self.transport).await
+    }
+
+    /// A client signing against the account S3 endpoint, or `None` if authorisation failed.
+    pub async fn signing_client(&self, session_token: &str) -> Option<S3CompatibleSigningClient> {
+        let (endpoint, region) = self.endpoint().await?;
+        Some(S3CompatibleSigningClient::new(
+            &self.account_id,
+            &self.account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        ))
+    }
+
+    async fn endpoint_with<T: AuthoriseTransport>(
+        &self,
+        transport: &T,
+    ) -> Option<(String, String)> {
+        let now = self.clock.now();
+        if let Some(value) = self
+            .cached
+            .lock()
+            .unwrap_or_else(|error| error.into_inner())
+            .as_ref()
+        {
+            if now < value.expires_at {
+                return Some((value.endpoint.clone(), value.region.clone()));
+            }
+        }
+        let result = authorise_with(
+            transport,
+            Provider::Backblaze,
+            &self.account_id,
+            &self.account_auth_token,
+        )
+        .await;
+        *self
+            .cached
+            .lock()
+            .unwrap_or_else(|error| error.into_inner()) =
+            result.as_ref().map(|(endpoint, region)| CachedEndpoint {
+                endpoint: endpoint.clone(),
+                region: region.clone(),
+                expires_at: now + chrono::Duration::hours(BACKBLAZE_AUTHORISATION_LIFETIME_HOURS),
+            });
+        result
+    }
+}
+
+async fn authorise_with<T: AuthoriseTransport>(
+    transport: &T,
+    provider: Provider,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+) -> Option<(String, String)> {
+    let header_value =
+        provider.authorisation_header(s3_compatible_account_id, s3_compatible_account_auth_token);
+    let body = match transport
+        .authorise(HeaderValue::from_str(&header_value).unwrap())
+        .await
+    {
+        Ok(value) => value,
+        Err(error) => {
+            console_log!("{error}");
+            return None;
+        }
+    };
+    provider.endpoint_from_response(&body)
+}
+
+async fn bucket_region(bucket_url: &str) -> Option<String> {
+    let client = reqwest::Client::new();
+    let response = match client.head(bucket_url).send().await {
+        Ok(value) => value,
+        Err(_) => {
+            console_log!("Unable to reach bucket for region discovery");
+            return None;
+        }
+    };
+    response
+        .headers()
+        .get("x-amz-bucket-region")
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
+}
+
+/// Finds the region a bucket lives in from the `x-amz-bucket-region` header S3 returns on an
+/// unauthenticated HEAD request, for use when the region is not known up front.
+#[wasm_bindgen]
+pub async fn discover_region(endpoint: &str, bucket_name: &str) -> Option<String> {
+    bucket_region(&format!("https://{bucket_name}.{endpoint}")).await
+}
+
+#[wasm_bindgen]
+pub async fn presigned_get_url(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        match signing_client.presigned_get_url(bucket_name, key, expiry) {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+#[wasm_bindgen]
+pub async fn presigned_put_url(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        match signing_client.presigned_put_url(bucket_name, key, expiry) {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+/// A presigned URL, along with the endpoint and region it was signed for.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct SignedUrlWithEndpoint {
+    pub url: String,
+    pub endpoint: String,
+    pub region: String,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn signed_url_with_endpoint<T: AuthoriseTransport>(
+    transport: &T,
+    method: &str,
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> Option<SignedUrlWithEndpoint> {
+    let (endpoint, region) = authorise_with(
+        transport,
+        Provider::Backblaze,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+    )
+    .await?;
+    let signing_client = S3CompatibleSigningClient::new(
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        &endpoint,
+        &region,
+        session_token,
+    );
+    let result = if method == "PUT" {
+        signing_client.presigned_put_url(bucket_name, key, expiry)
+    } else {
+        signing_client.presigned_get_url(bucket_name, key, expiry)
+    };
+    match result {
+        Ok(url) => Some(SignedUrlWithEndpoint {
+            url,
+            endpoint,
+            region,
+        }),
+        Err(error) => {
+            console_log!("{error}");
+            None
+        }
+    }
+}
+
+/// Like `presigned_get_url`, but resolves to `{ url, endpoint, region }`, for showing where the
+/// URL points.
+#[wasm_bindgen]
+pub async fn presigned_get_url_with_endpoint(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> JsValue {
+    let result = signed_url_with_endpoint(
+        &ApiTransport::default(),
+        "GET",
+        key,
+        bucket_name,
+        expiry,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        session_token,
+    )
+    .await;
+    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
+}
+
+/// Like `presigned_put_url`, but resolves to `{ url, endpoint, region }`, for showing where the
+/// URL points.
+#[wasm_bindgen]
+pub async fn presigned_put_url_with_endpoint(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> JsValue {
+    let result = signed_url_with_endpoint(
+        &ApiTransport::default(),
+        "PUT",
+        key,
+        bucket_name,
+        expiry,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        session_token,
+    )
+    .await;
+    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
+}
+
+/// Like `presigned_get_url`, but resolves to `{ origin, pathname, searchParams }`, split as by
+/// the `URL` class, so the caller need not parse the URL again.
+#[wasm_bindgen]
+pub async fn presigned_get_url_parts(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> JsValue {
+    let result = signed_url_with_endpoint(
+        &ApiTransport::default(),
+        "GET",
+        key,
+        bucket_name,
+        expiry,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        session_token,
+    )
+    .await
+    .and_then(|value| url_parts(&value.url));
+    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
+}
+
+/// Like `presigned_put_url`, but resolves to `{ origin, pathname, searchParams }`, split as by
+/// the `URL` class, so the caller need not parse the URL again.
+#[wasm_bindgen]
+pub async fn presigned_put_url_parts(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> JsValue {
+    let result = signed_url_with_endpoint(
+        &ApiTransport::default(),
+        "PUT",
+        key,
+        bucket_name,
+        expiry,
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        session_token,
+    )
+    .await
+    .and_then(|value| url_parts(&value.url));
+    serde_wasm_bindgen::to_value(&result).unwrap_or(JsValue::NULL)
+}
+
+/// Authorises once, then returns a session which signs each part, complete and abort URL of the
+/// upload as it is needed.
+#[wasm_bindgen]
+pub async fn multipart_session(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    upload_id: &str,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> Result<MultipartSession, JsValue> {
+    let (endpoint, region) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token)
+            .await
+            .ok_or_else(|| JsValue::from_str("Error getting auth from backblaze"))?;
+    let signing_client = S3CompatibleSigningClient::new(
+        s3_compatible_account_id,
+        s3_compatible_account_auth_token,
+        &endpoint,
+        &region,
+        session_token,
+    );
+    Ok(MultipartSession::new(
+        signing_client,
+        bucket_name,
+        key,
+        upload_id,
+        expiry,
+    ))
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub async fn presigned_multipart_put_url(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    parts: u32,
+    upload_id: &str,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        let data = PresignedMultipartParameters {
+            bucket: bucket_name,
+            key,
+            parts,
+            upload_id,
+            expiry,
+        };
+        match signing_client.presigned_multipart_put_url(&data) {
+            Ok(value) => serde_json::to_string(&value).unwrap(),
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub async fn presigned_multipart_put_url_for_parts(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    part_numbers: Vec<u32>,
+    upload_id: &str,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        match signing_client.presigned_multipart_put_url_for_parts(
+            bucket_name,
+            key,
+            upload_id,
+            &part_numbers,
+            expiry,
+        ) {
+            Ok(value) => serde_json::to_string(&value).unwrap(),
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+/// Streams multipart upload URLs to `callback`, one newline-delimited JSON line (`{ "partNumber":
+/// n, "url": "..." }`) per call, rather than returning them all in one string.  Resolves to the
+/// number of lines sent.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub async fn presigned_multipart_put_url_stream(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    parts: u32,
+    upload_id: &str,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+    callback: &js_sys::Function,
+) -> u32 {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        let data = PresignedMultipartParameters {
+            bucket: bucket_name,
+            key,
+            parts,
+            upload_id,
+            expiry,
+        };
+        let result = signing_client.presigned_multipart_put_url_ndjson(&data, |line| {
+            if callback
+                .call1(&JsValue::NULL, &JsValue::from_str(&format!("{line}\n")))
+                .is_err()
+            {
+                console_log!("Error calling multipart stream callback");
+            }
+        });
+        match result {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                0
+            }
+        }
+    } else {
+        0
+    }
+}
+
+/// Resolves once the JavaScript event loop has had a turn, so the page can render and handle
+/// input.  A timer, rather than a microtask, is needed, as microtasks run before rendering.
+#[cfg(target_arch = "wasm32")]
+async fn yield_to_event_loop() {
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let set_timeout = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("setTimeout"))
+            .ok()
+            .and_then(|value| value.dyn_into::<js_sys::Function>().ok());
+        let scheduled = match set_timeout {
+            Some(set_timeout) => set_timeout
+                .call2(&JsValue::NULL, resolve.as_ref(), &JsValue::from(0))
+                .is_ok(),
+            None => false,
+        };
+        if !scheduled {
+            let _ = resolve.call0(&JsValue::NULL);
+        }
+    });
+    let _ = wasm_bindgen_futures::JsFuture::from(promise).await;
+}
+
+// There is no JavaScript event loop outside WASM, so native builds carry straight on
+#[cfg(not(target_arch = "wasm32"))]
+async fn yield_to_event_loop() {}
+
+/// Signs multipart upload URLs `chunk_size` at a time, giving the event loop a turn between
+/// chunks so very large uploads do not block the main thread.  `callback` is called with
+/// `(done, total)` after each chunk.  Resolves to the URLs as a JSON array, or an empty string on
+/// error.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub async fn presigned_multipart_put_url_chunked(
+    key: &str,
+    bucket_name: &str,
+    expiry: u32,
+    parts: u32,
+    upload_id: &str,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+    chunk_size: u32,
+    callback: &js_sys::Function,
+) -> String {
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        let data = PresignedMultipartParameters {
+            bucket: bucket_name,
+            key,
+            parts,
+            upload_id,
+            expiry,
+        };
+        let result = signing_client
+            .presigned_multipart_put_url_chunked(
+                &data,
+                chunk_size,
+                |done, total| {
+                    if callback
+                        .call2(&JsValue::NULL, &JsValue::from(done), &JsValue::from(total))
+                        .is_err()
+                    {
+                        console_log!("Error calling multipart progress callback");
+                    }
+                },
+                yield_to_event_loop,
+            )
+            .await;
+        match result {
+            Ok(value) => serde_json::to_string(&value).unwrap(),
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+#[wasm_bindgen]
+pub async fn presigned_get_url_from_request(
+    request: JsValue,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    let request: GetRequest = match serde_wasm_bindgen::from_value(request) {
+        Ok(value) => value,
+        Err(_) => {
+            console_log!("Unable to parse GET request options");
+            return String::from("");
+        }
+    };
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        match signing_client.presigned_get_url_for_request(&request) {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+#[wasm_bindgen]
+pub async fn presigned_put_url_from_request(
+    request: JsValue,
+    s3_compatible_account_id: &str,
+    s3_compatible_account_auth_token: &str,
+    session_token: &str,
+) -> String {
+    let request: PutRequest = match serde_wasm_bindgen::from_value(request) {
+        Ok(value) => value,
+        Err(_) => {
+            console_log!("Unable to parse PUT request options");
+            return String::from("");
+        }
+    };
+    if let Some((endpoint, region)) =
+        authorise_backblaze_b2(s3_compatible_account_id, s3_compatible_account_auth_token).await
+    {
+        let signing_client = S3CompatibleSigningClient::new(
+            s3_compatible_account_id,
+            s3_compatible_account_auth_token,
+            &endpoint,
+            &region,
+            session_token,
+        );
+        match signing_client.presigned_put_url_for_request(&request) {
+            Ok(value) => value,
+            Err(error) => {
+                console_log!("{error}");
+                String::from("")
+            }
+        }
+    } else {
+        String::from("")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        authorise_with, backblaze_endpoint, bucket_region, region_from_s3_api_url,
+        signed_url_with_endpoint, ApiTransport, AuthoriseTransport, BackblazeAuthoriser,
+        BackblazeSession, ConnectionPool, Provider, ERROR_BODY_SNIPPET_LENGTH,
+        MAXIMUM_AUTH_RESPONSE_LENGTH,
+    };
+    use crate::s3_compatible_signing_client::{Clock, S3CompatibleSigningClient};
+    use chrono::{DateTime, Utc};
+    use reqwest::header::HeaderValue;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use url::Url;
+
+    /// Self-signed certificate for `minio.example.com`, standing in for a private CA.
+    const TEST_CA_CERTIFICATE: &str = "-----BEGIN CERTIFICATE-----
+MIIBjzCCATWgAwIBAgIUKAEpj3HUB15BITKbPQZAhrv8DRcwCgYIKoZIzj0EAwIw
+HDEaMBgGA1UEAwwRbWluaW8uZXhhbXBsZS5jb20wIBcNMjYxMDE2MDg1MDA1WhgP
+MjEyNjA5MjIwODUwMDVaMBwxGjAYBgNVBAMMEW1pbmlvLmV4YW1wbGUuY29tMFkw
+EwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAEICm7nF4NVDqSdx9aImKhuww8tegk6Z8K
+v0maqqhqCzzt8mtzgvR74rTWJE17C7MM0LvFkHz2i72W34Qr5kyw6aNTMFEwHQYD
+VR0OBBYEFMXo4oJ/mNWFBd0zv95J29kkfI/lMB8GA1UdIwQYMBaAFMXo4oJ/mNWF
+Bd0zv95J29kkfI/lMA8GA1UdEwEB/wQFMAMBAf8wCgYIKoZIzj0EAwIDSAAwRQIg
+c7Mhy9mipmt7ITobXobgYJIiw+MeBYnU7HXc7KAIw0MCIQDH0oF5gOBy3/0UOtm5
+lz58+3TTdy1KTu2d0m8/FGPDJw==
+-----END CERTIFICATE-----
+";
+
+    const BACKBLAZE_AUTH_RESPONSE: &str = r#"{
+        "absoluteMinimumPartSize": 5000000,
+        "accountId": "AKIDEXAMPLE",
+        "apiUrl": "https://api004.backblazeb2.com",
+        "authorizationToken": "4_0022623512fc8f80000000001_0186e431_d18d02_acct_tH7VW03boebOXayIc43-sxptpfA=",
+        "downloadUrl": "https://f004.backblazeb2.com",
+        "recommendedPartSize": 100000000,
+        "s3ApiUrl": "https://s3.us-west-004.backblazeb2.com"
+    }"#;
+
+    struct MockTransport {
+        response: &'static str,
+    }
+
+    impl AuthoriseTransport for MockTransport {
+        async fn authorise(&self, authorisation_header: HeaderValue) -> Result<String, String> {
+            assert_eq!(
+                authorisation_header,
+                "Basic QUtJREVYQU1QTEU6d0phbHJYVXRuRkVNSS9LN01ERU5HK2JQeFJmaUNZRVhBTVBMRUtFWQ=="
+            );
+            Ok(self.response.to_string())
+        }
+    }
+
+    struct CountingTransport {
+        calls: AtomicU32,
+    }
+
+    impl AuthoriseTransport for CountingTransport {
+        async fn authorise(&self, _authorisation_header: HeaderValue) -> Result<String, String> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            Ok(BACKBLAZE_AUTH_RESPONSE.to_string())
+        }
+    }
+
+    struct SharedClock(Arc<Mutex<DateTime<Utc>>>);
+
+    impl Clock for SharedClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[tokio::test]
+    pub async fn test_bucket_region() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("HEAD", "/")
+            .with_status(403)
+            .with_header("x-amz-bucket-region", "eu-west-2")
+            .create_async()
+            .await;
+
+        let region = bucket_region(&server.url()).await;
+        mock.assert_async().await;
+        assert_eq!(region, Some("eu-west-2".to_string()));
+    }
+
+    #[test]
+    pub fn test_region_from_s3_api_url() {
+        assert_eq!(
+            region_from_s3_api_url("s3.us-west-004.backblazeb2.com"),
+            Some("us-west-004")
+        );
+        assert_eq!(
+            region_from_s3_api_url("s3.eu-central-003.backblazeb2.com"),
+            Some("eu-central-003")
+        );
+
+        // the region is signed verbatim, so the credential scope carries the Backblaze label
+        let signing_client = S3CompatibleSigningClient::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "s3.eu-central-003.backblazeb2.com",
+            "eu-central-003",
+            "",
+        );
+        let url = signing_client
+            .presigned_get_url("example-bucket", "my-movie.m2ts", 600)
+            .unwrap();
+        assert!(url.contains("%2Feu-central-003%2Fs3%2Faws4_request&"));
+    }
+
+    #[tokio::test]
+    pub async fn test_authorise_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/b2api/v2/b2_authorize_account")
+            .with_status(500)
+            .with_header("content-type", "text/html")
+            .with_body("<html><body><h1>500 Internal Server Error</h1></body></html>")
+            .create_async()
+            .await;
+        let transport = ApiTransport {
+            url: format!("{}/b2api/v2/b2_authorize_account", server.url()),
+            ..Default::default()
+        };
+
+        let result = transport
+            .authorise(HeaderValue::from_static("Basic QUtJREVYQU1QTEU6"))
+            .await;
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            Err(String::from(
+                "Backblaze auth failed with status 500 Internal Server Error: <html><body><h1>500 Internal Server Error</h1></body></html>"
+            ))
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_authorise_oversized_error_status() {
+        let mut server = mockito::Server::new_async().await;
+        let body = format!(
+            "<html><body>{}</body></html>",
+            "x".repeat(MAXIMUM_AUTH_RESPONSE_LENGTH)
+        );
+        let mock = server
+            .mock("GET", "/b2api/v2/b2_authorize_account")
+            .with_status(503)
+            .with_header("content-type", "text/html")
+            .with_body(&body)
+            .create_async()
+            .await;
+        let transport = ApiTransport {
+            url: format!("{}/b2api/v2/b2_authorize_account", server.url()),
+            ..Default::default()
+        };
+
+        let result = transport
+            .authorise(HeaderValue::from_static("Basic QUtJREVYQU1QTEU6"))
+            .await;
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            Err(format!(
+                "Backblaze auth failed with status 503 Service Unavailable: {}",
+                &body[..ERROR_BODY_SNIPPET_LENGTH]
+            ))
+        );
+    }
+
+    #[tokio::test]
+    pub async fn test_authorise_with_mock_transport() {
+        let transport = MockTransport {
+            response: BACKBLAZE_AUTH_RESPONSE,
+        };
+        let id = "AKIDEXAMPLE";
+        let key = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        let (endpoint, region) = authorise_with(&transport, Provider::Backblaze, id, key)
+            .await
+            .unwrap();
+        assert_eq!(endpoint, "s3.us-west-004.backblazeb2.com");
+        assert_eq!(region, "us-west-004");
+
+        let signing_client = S3CompatibleSigningClient::new(
+            id,
+            key,
+            &endpoint,
+            &region,
+            "session-claqbxlfv0000ix0lx6inf7sd",
+        );
+        let url = signing_client
+            .presigned_get_url("example-bucket", "my-movie.m2ts", 600)
+            .unwrap();
+        let url = Url::parse(&url).unwrap();
+        assert_eq!(
+            url.host_str(),
+            Some("example-bucket.s3.us-west-004.backblazeb2.com")
+        );
+        let credential = url
+            .query_pairs()
+            .find(|(name, _)| name == "X-Amz-Credential")
+            .map(|(_, value)| value.to_string())
+            .unwrap();
+        assert!(credential.ends_with("/us-west-004/s3/aws4_request"));
+    }
+
+    #[tokio::test]
+    pub async fn test_signed_url_with_endpoint() {
+        let transport = MockTransport {
+            response: BACKBLAZE_AUTH_RESPONSE,
+        };
+        let result = signed_url_with_endpoint(
+            &transport,
+            "GET",
+            "my-movie.m2ts",
+            "example-bucket",
+            600,
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            "session-claqbxlfv0000ix0lx6inf7sd",
+        )
+        .await
+        .unwrap();
+        assert_eq!(result.endpoint, "s3.us-west-004.backblazeb2.com");
+        assert_eq!(result.region, "us-west-004");
+        assert!(result
+            .url
+            .starts_with("https://example-bucket.s3.us-west-004.backblazeb2.com/my-movie.m2ts?"));
+    }
+
+    #[tokio::test]
+    pub async fn test_authoriser_with_connection_pool() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/b2api/v2/b2_authorize_account")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(BACKBLAZE_AUTH_RESPONSE)
+            .expect(2)
+            .create_async()
+            .await;
+        let pool = ConnectionPool::default()
+            .with_idle_timeout(Duration::from_secs(30))
+            .with_max_idle_per_host(4);
+        let authoriser = BackblazeAuthoriser {
+            transport: ApiTransport::with_pool(
+                &format!("{}/b2api/v2/b2_authorize_account", server.url()),
+                &pool,
+            )
+            .unwrap(),
+        };
+
+        let id = "AKIDEXAMPLE";
+        let key = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+        for _ in 0..2 {
+            assert_eq!(
+                authoriser.authorise(id, key).await,
+                Some((
+                    "s3.us-west-004.backblazeb2.com".to_string(),
+                    "us-west-004".to_string()
+                ))
+            );
+        }
+        mock.assert_async().await;
+    }
+
+    #[test]
+    pub fn test_connection_pool_root_certificate() {
+        let pool = ConnectionPool::default()
+            .with_root_certificate(TEST_CA_CERTIFICATE.as_bytes())
+            .unwrap();
+        assert_eq!(pool.root_certificates.len(), 1);
+        assert!(pool.client().is_ok());
+
+        let pool = pool.danger_accept_invalid_certificates(true);
+        assert!(pool.client().is_ok());
+
+        assert!(ConnectionPool::default()
+            .with_root_certificate(b"not a certificate")
+            .is_err());
+    }
+
+    #[test]
+    pub fn test_backblaze_endpoint() {
+        assert_eq!(
+            backblaze_endpoint(BACKBLAZE_AUTH_RESPONSE),
+            Some((
+                "s3.us-west-004.backblazeb2.com".to_string(),
+                "us-west-004".to_string()
+            ))
+        );
+        assert_eq!(
+            Provider::Backblaze.endpoint_from_response(BACKBLAZE_AUTH_RESPONSE),
+            backblaze_endpoint(BACKBLAZE_AUTH_RESPONSE)
+        );
+        assert_eq!(backblaze_endpoint(r#"{ "s3ApiUrl": "not a url" }"#), None);
+    }
+
+    #[tokio::test]
+    pub async fn test_authorise_oversized_response() {
+        let mut server = mockito::Server::new_async().await;
+        let mock = server
+            .mock("GET", "/b2api/v2/b2_authorize_account")
+            .with_status(200)
+            .with_header("content-type", "application/json")
+            .with_body(" ".repeat(MAXIMUM_AUTH_RESPONSE_LENGTH + 1))
+            .create_async()
+            .await;
+        let transport = ApiTransport {
+            url: format!("{}/b2api/v2/b2_authorize_account", server.url()),
+            ..Default::default()
+        };
+
+        let result = transport
+            .authorise(HeaderValue::from_static("Basic QUtJREVYQU1QTEU6"))
+            .await;
+        mock.assert_async().await;
+        assert_eq!(
+            result,
+            Err(format!(
+                "Auth response is longer than {MAXIMUM_AUTH_RESPONSE_LENGTH} bytes"
+            ))
+        );
+
+        // a body sent without a length is still cut off once it passes the limit
+        let mock = server
+            .mock("GET", "/b2api/v2/b2_authorize_account")
+            .with_status(200)
+            .with_chunked_body(|writer| writer.write_all(&[b' '; 64]))
+            .create_async()
+            .await;
+        let authoriser = BackblazeAuthoriser {
+            transport: ApiTransport {
+                url: format!("{}/b2api/v2/b2_authorize_account", server.url()),
+                ..Default::default()
+            },
+        }
+        .with_maximum_response_length(32);
+        assert_eq!(
+            authoriser
+                .transport
+                .authorise(HeaderValue::from_static("Basic QUtJREVYQU1QTEU6"))
+                .await,
+            Err(String::from("Auth response is longer than 32 bytes"))
+        );
+        mock.assert_async().await;
+    }
+
+    #[tokio::test]
+    pub async fn test_session_reauthorises_on_expiry() {
+        let time = DateTime::parse_from_rfc3339("2015-08-30T12:36:00Z")
+            .unwrap()
+            .with_timezone::<Utc>(&Utc);
+        let now = Arc::new(Mutex::new(time));
+        let session = BackblazeSession::new(
+            "AKIDEXAMPLE",
+            "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+            &ConnectionPool::default(),
+        )
+        .unwrap()
+        .with_clock(SharedClock(Arc::clone(&now)));
+        let transport = CountingTransport {
+            calls: AtomicU32::new(0),
+        };
+
+        let expected = Some((
+            "s3.us-west-004.backblazeb2.com".to_string(),
+            "us-west-004".to_string(),
+        ));
+        assert_eq!(session.endpoint_with(&transport).await, expected);
+        *now.lock().unwrap() = time + chrono::Duration::hours(23);
+        assert_eq!(session.endpoint_with(&transport).await, expected);
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 1);
+
+        // a day after the first authorisation, its token has expired
+        *now.lock().unwrap() = time + chrono::Duration::hours(24);
+        assert_eq!(session.endpoint_with(&transport).await, expected);
+        assert_eq!(transport.calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,111 @@
+use std::fmt;
+
+use wasm_bindgen::JsValue;
+
+/// Reasons a URL cannot be presigned as requested.
+#[derive(Clone, Debug, PartialEq)]
+pub enum PresignError {
+    /// The endpoint names a different region to the one the client signs for.
+    RegionMismatch {
+        endpoint_region: String,
+        region: String,
+    },
+    /// The expiry, in seconds, is longer than SigV4 allows.
+    ExpiryTooLong { expiry: u32, maximum: u32 },
+    /// The expiry, in seconds, is shorter than the client minimum.
+    ExpiryTooShort { expiry: u32, minimum: u32 },
+    /// The object key, in UTF-8 bytes, is longer than S3 accepts.
+    KeyTooLong { length: usize, maximum: usize },
+    /// The signing time plus the expiry is later than can be represented.
+    ExpiryOverflow,
+    /// A multipart upload was asked to sign no parts.
+    NoParts,
+    /// A multipart part number is outside the range S3 accepts, from 1 to `maximum`.
+    InvalidPartNumber { part_number: u32, maximum: u32 },
+    /// A multipart part number is listed more than once.
+    DuplicatePartNumber { part_number: u32 },
+    /// A multipart upload cannot be split into parts of this many bytes.
+    InvalidPartSize { part_size: u64 },
+    /// The payload hash to sign is not a lower-case hex-encoded SHA256.
+    InvalidContentSha256(String),
+    /// The signed connectivity check request could not be sent.
+    Unreachable(String),
+    /// The connection pool settings could not be applied to an HTTP client.
+    InvalidConnectionPool(String),
+    /// The signed connectivity check request was refused with this HTTP status.
+    ConnectivityFailed { status: u16 },
+    /// The URL to sign has no host.
+    MissingHost,
+    /// The date to sign is not an RFC 3339 time.
+    InvalidDate(String),
+    /// The URL to re-sign could not be parsed as a presigned URL.
+    InvalidUrl(String),
+    /// The URL to re-sign has signed headers other than `host`, whose values it does not carry.
+    UnresignableHeaders(String),
+}
+
+impl fmt::Display for PresignError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PresignError::RegionMismatch {
+                endpoint_region,
+                region,
+            } => write!(
+                f,
+                "Endpoint is in region `{endpoint_region}`, but signing for region `{region}`"
+            ),
+            PresignError::ExpiryTooLong { expiry, maximum } => write!(
+                f,
+                "Expiry of {expiry} seconds is longer than the maximum of {maximum} seconds"
+            ),
+            PresignError::ExpiryTooShort { expiry, minimum } => write!(
+                f,
+                "Expiry of {expiry} seconds is shorter than the minimum of {minimum} seconds"
+            ),
+            PresignError::KeyTooLong { length, maximum } => write!(
+                f,
+                "Key of {length} bytes is longer than the maximum of {maximum} bytes"
+            ),
+            PresignError::ExpiryOverflow => {
+                write!(f, "Expiry is too far in the future to represent")
+            }
+            PresignError::NoParts => write!(f, "Multipart upload needs at least one part"),
+            PresignError::InvalidPartNumber {
+                part_number,
+                maximum,
+            } => write!(
+                f,
+                "Part number {part_number} is outside the range 1 to {maximum}"
+            ),
+            PresignError::DuplicatePartNumber { part_number } => {
+                write!(f, "Part number {part_number} is listed more than once")
+            }
+            PresignError::InvalidPartSize { part_size } => {
+                write!(f, "Unable to split upload into parts of {part_size} bytes")
+            }
+            PresignError::InvalidContentSha256(hash) => {
+                write!(f, "`{hash}` is not a lower-case hex-encoded SHA256")
+            }
+            PresignError::Unreachable(error) => write!(f, "Unable to reach endpoint: {error}"),
+            PresignError::InvalidConnectionPool(error) => write!(f, "{error}"),
+            PresignError::ConnectivityFailed { status } => {
+                write!(f, "Connectivity check failed with status {status}")
+            }
+            PresignError::MissingHost => write!(f, "Unable to find host for presigned URL"),
+            PresignError::InvalidDate(date) => write!(f, "`{date}` is not an RFC 3339 date"),
+            PresignError::InvalidUrl(url) => write!(f, "`{url}` is not a valid presigned URL"),
+            PresignError::UnresignableHeaders(signed_headers) => write!(
+                f,
+                "Unable to re-sign URL with signed headers `{signed_headers}`, as it does not carry their values"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PresignError {}
+
+impl From<PresignError> for JsValue {
+    fn from(error: PresignError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
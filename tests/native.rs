@@ -0,0 +1,26 @@
+//! Checks the signing client can be used from a native (non-WASM) build, for example server-side.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+use s3_presigned_urls::S3CompatibleSigningClient;
+
+#[test]
+pub fn test_signing_client_native() {
+    let signing_client = S3CompatibleSigningClient::new(
+        "AKIDEXAMPLE",
+        "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY",
+        "s3.amazonaws.com",
+        "us.east-1",
+        "session-claqbxlfv0000ix0lx6inf7sd",
+    );
+
+    let url = signing_client
+        .presigned_get_url("example-bucket", "my-movie.m2ts", 600)
+        .unwrap();
+    assert!(url.starts_with("https://example-bucket.s3.amazonaws.com/my-movie.m2ts?"));
+    assert!(url.contains("&X-Amz-Signature="));
+    assert_eq!(
+        s3_presigned_urls::required_client_headers(&url),
+        Vec::<String>::new()
+    );
+}